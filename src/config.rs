@@ -0,0 +1,84 @@
+use std::fmt;
+
+/// Hash algorithms supported by the Linux fs-verity built-in signature
+/// verification mechanism.
+///
+/// The numeric values match `FS_VERITY_HASH_ALG_*` in
+/// `include/uapi/linux/fsverity.h` and must not be changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsVerityHashAlg {
+    Sha256 = 1,
+    Sha512 = 2,
+}
+
+impl FsVerityHashAlg {
+    /// The `digest_algorithm` value stored in `fsverity_formatted_digest`
+    /// and `fsverity_descriptor`.
+    pub fn id(self) -> u16 {
+        self as u16
+    }
+
+    /// Size, in bytes, of a digest produced by this algorithm.
+    pub fn digest_size(self) -> usize {
+        match self {
+            FsVerityHashAlg::Sha256 => 32,
+            FsVerityHashAlg::Sha512 => 64,
+        }
+    }
+
+    /// Looks up the algorithm for a raw `FS_VERITY_HASH_ALG_*` id, e.g. one
+    /// reported by the kernel in `fsverity_digest::digest_algorithm`.
+    /// Returns `None` for an id this crate doesn't recognize.
+    pub fn from_id(id: u16) -> Option<Self> {
+        match id {
+            1 => Some(FsVerityHashAlg::Sha256),
+            2 => Some(FsVerityHashAlg::Sha512),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FsVerityHashAlg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FsVerityHashAlg::Sha256 => write!(f, "sha256"),
+            FsVerityHashAlg::Sha512 => write!(f, "sha512"),
+        }
+    }
+}
+
+/// Default Merkle tree block size used by `mkfs.ext4`/`mkfs.f2fs` and by
+/// `fsverity-utils` when none is specified.
+pub const DEFAULT_BLOCK_SIZE: usize = 4096;
+
+/// Parameters controlling how an fs-verity Merkle tree is built.
+///
+/// These three values (together with the file contents) fully determine
+/// the resulting digest, so they must match between the producer and the
+/// kernel (or offline verifier) for a measurement to be meaningful.
+#[derive(Debug, Clone)]
+pub struct FsVerityConfig {
+    pub hash_alg: FsVerityHashAlg,
+    pub block_size: usize,
+    pub salt: Vec<u8>,
+}
+
+impl Default for FsVerityConfig {
+    fn default() -> Self {
+        FsVerityConfig {
+            hash_alg: FsVerityHashAlg::Sha256,
+            block_size: DEFAULT_BLOCK_SIZE,
+            salt: Vec::new(),
+        }
+    }
+}
+
+impl FsVerityConfig {
+    pub fn new(hash_alg: FsVerityHashAlg, block_size: usize, salt: Vec<u8>) -> Self {
+        FsVerityConfig {
+            hash_alg,
+            block_size,
+            salt,
+        }
+    }
+}
@@ -0,0 +1,469 @@
+use std::io;
+
+use digest::generic_array::GenericArray;
+use digest::typenum::Unsigned;
+use digest::{Digest, FixedOutput, FixedOutputReset, HashMarker, OutputSizeUser, Reset, Update};
+use sha2::{Sha256, Sha512};
+
+use crate::config::{FsVerityConfig, FsVerityHashAlg};
+
+/// On-disk fs-verity descriptor hashed to produce the final file digest.
+///
+/// This mirrors `struct fsverity_descriptor` from
+/// `include/uapi/linux/fsverity.h`; every field is little-endian and the
+/// struct is always `256` bytes regardless of hash algorithm.
+#[repr(C)]
+struct FsVerityDescriptor {
+    version: u8,
+    hash_algorithm: u8,
+    log_blocksize: u8,
+    salt_size: u8,
+    sig_size: u32,
+    data_size: u64,
+    root_hash: [u8; 64],
+    salt: [u8; 32],
+    reserved: [u8; 144],
+}
+
+impl FsVerityDescriptor {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(256);
+        buf.push(self.version);
+        buf.push(self.hash_algorithm);
+        buf.push(self.log_blocksize);
+        buf.push(self.salt_size);
+        buf.extend_from_slice(&self.sig_size.to_le_bytes());
+        buf.extend_from_slice(&self.data_size.to_le_bytes());
+        buf.extend_from_slice(&self.root_hash);
+        buf.extend_from_slice(&self.salt);
+        buf.extend_from_slice(&self.reserved);
+        buf
+    }
+}
+
+/// The final fs-verity measurement of a file: the hash algorithm used and
+/// the resulting digest bytes, as reported by `FS_IOC_MEASURE_VERITY`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsVerityDigest {
+    pub hash_alg: FsVerityHashAlg,
+    pub digest: Vec<u8>,
+}
+
+pub(crate) fn hash_block(hash_alg: FsVerityHashAlg, salt: &[u8], block: &[u8]) -> Vec<u8> {
+    match hash_alg {
+        FsVerityHashAlg::Sha256 => {
+            let mut hasher = Sha256::new();
+            Digest::update(&mut hasher, salt);
+            Digest::update(&mut hasher, block);
+            hasher.finalize().to_vec()
+        }
+        FsVerityHashAlg::Sha512 => {
+            let mut hasher = Sha512::new();
+            Digest::update(&mut hasher, salt);
+            Digest::update(&mut hasher, block);
+            hasher.finalize().to_vec()
+        }
+    }
+}
+
+/// Pads `block` up to `block_size` with zero bytes, returning a new buffer.
+pub(crate) fn zero_pad(block: &[u8], block_size: usize) -> Vec<u8> {
+    let mut padded = block.to_vec();
+    padded.resize(block_size, 0);
+    padded
+}
+
+/// Concatenates `digests` into a single Merkle tree block, zero-padding it
+/// up to `block_size` — the one place every level-folding implementation in
+/// this crate builds the bytes that get hashed into a parent digest.
+pub(crate) fn pack_digest_block<'a>(block_size: usize, digests: impl IntoIterator<Item = &'a [u8]>) -> Vec<u8> {
+    let mut block = Vec::with_capacity(block_size);
+    for digest in digests {
+        block.extend_from_slice(digest);
+    }
+    zero_pad(&block, block_size)
+}
+
+/// Hashes the Merkle tree leaf (data block) digests for `data`, zero-padding
+/// the final, partially filled block. An empty file still produces a single
+/// digest, over one block of zero padding.
+pub(crate) fn hash_leaves(config: &FsVerityConfig, data: &[u8]) -> Vec<Vec<u8>> {
+    let mut level: Vec<Vec<u8>> = data
+        .chunks(config.block_size)
+        .map(|block| hash_block(config.hash_alg, &config.salt, &zero_pad(block, config.block_size)))
+        .collect();
+
+    if level.is_empty() {
+        level.push(hash_block(
+            config.hash_alg,
+            &config.salt,
+            &zero_pad(&[], config.block_size),
+        ));
+    }
+
+    level
+}
+
+/// Folds one level of the Merkle tree into the digests that make up the
+/// next level up, zero-padding the final, partially filled parent block.
+/// `on_block` is called with each packed block before it is hashed, so
+/// callers that need to persist the tree (e.g. [`export_merkle_tree`]) can
+/// do so without duplicating the folding loop.
+fn fold_level(
+    hash_alg: FsVerityHashAlg,
+    salt: &[u8],
+    block_size: usize,
+    digests: &[Vec<u8>],
+    mut on_block: impl FnMut(&[u8]) -> io::Result<()>,
+) -> io::Result<Vec<Vec<u8>>> {
+    let digests_per_block = block_size / hash_alg.digest_size();
+    digests
+        .chunks(digests_per_block)
+        .map(|chunk| {
+            let block = pack_digest_block(block_size, chunk.iter().map(Vec::as_slice));
+            on_block(&block)?;
+            Ok(hash_block(hash_alg, salt, &block))
+        })
+        .collect()
+}
+
+/// Hashes one level of the Merkle tree into the digests that make up the
+/// next level up, zero-padding the final, partially filled parent block.
+fn hash_level(hash_alg: FsVerityHashAlg, salt: &[u8], block_size: usize, digests: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    fold_level(hash_alg, salt, block_size, digests, |_| Ok(())).expect("no-op callback never fails")
+}
+
+/// Computes the Merkle tree root hash for `data`, i.e. the value stored in
+/// `fsverity_descriptor::root_hash`.
+fn merkle_tree_root(config: &FsVerityConfig, data: &[u8]) -> Vec<u8> {
+    let mut level = hash_leaves(config, data);
+
+    while level.len() > 1 {
+        level = hash_level(config.hash_alg, &config.salt, config.block_size, &level);
+    }
+
+    level.remove(0)
+}
+
+/// Builds the bytes of `struct fsverity_descriptor` for `data_size` bytes of
+/// file content with the given Merkle tree `root_hash`.
+pub(crate) fn descriptor_bytes(config: &FsVerityConfig, data_size: u64, root_hash: &[u8]) -> Vec<u8> {
+    let mut salt = [0u8; 32];
+    salt[..config.salt.len()].copy_from_slice(&config.salt);
+    let mut root_hash_field = [0u8; 64];
+    root_hash_field[..root_hash.len()].copy_from_slice(root_hash);
+
+    FsVerityDescriptor {
+        version: 1,
+        hash_algorithm: config.hash_alg.id() as u8,
+        log_blocksize: config.block_size.trailing_zeros() as u8,
+        salt_size: config.salt.len() as u8,
+        sig_size: 0,
+        data_size,
+        root_hash: root_hash_field,
+        salt,
+        reserved: [0u8; 144],
+    }
+    .to_bytes()
+}
+
+/// Hashes an already-serialized `fsverity_descriptor` into the final
+/// fs-verity digest, i.e. what `FS_IOC_MEASURE_VERITY` reports.
+pub(crate) fn digest_from_descriptor(hash_alg: FsVerityHashAlg, descriptor: &[u8]) -> FsVerityDigest {
+    FsVerityDigest {
+        hash_alg,
+        digest: hash_block(hash_alg, &[], descriptor),
+    }
+}
+
+/// Computes the fs-verity digest of `data` the same way the kernel would
+/// when enabling verity on a file with the given configuration.
+pub fn compute_digest(config: &FsVerityConfig, data: &[u8]) -> FsVerityDigest {
+    let root_hash = merkle_tree_root(config, data);
+    let descriptor = descriptor_bytes(config, data.len() as u64, &root_hash);
+    digest_from_descriptor(config.hash_alg, &descriptor)
+}
+
+/// Serializes the full Merkle tree for `data` to `writer`, one level at a
+/// time from the data-block hashes up to (but not including) the root,
+/// followed by the `fsverity_descriptor` bytes — the same layout the
+/// kernel maintains on disk for a verity file, so it can be persisted and
+/// later replayed with [`crate::verify::verify_offline`] on platforms
+/// without fs-verity support.
+///
+/// Returns the same digest [`compute_digest`] would have computed.
+pub fn export_merkle_tree<W: std::io::Write>(
+    config: &FsVerityConfig,
+    data: &[u8],
+    writer: &mut W,
+) -> std::io::Result<FsVerityDigest> {
+    let mut level = hash_leaves(config, data);
+
+    while level.len() > 1 {
+        level = fold_level(config.hash_alg, &config.salt, config.block_size, &level, |block| writer.write_all(block))?;
+    }
+    let root_hash = level.remove(0);
+
+    let descriptor = descriptor_bytes(config, data.len() as u64, &root_hash);
+    writer.write_all(&descriptor)?;
+
+    Ok(digest_from_descriptor(config.hash_alg, &descriptor))
+}
+
+/// Associates a RustCrypto `Digest` implementation with the fs-verity hash
+/// algorithm ID it corresponds to, so [`FsVerityHasher`] can be generic
+/// over the underlying hash function while still filling in
+/// `fsverity_descriptor::hash_algorithm` correctly.
+pub trait FsVerityAlgorithm: Digest + Clone {
+    const HASH_ALG: FsVerityHashAlg;
+}
+
+impl FsVerityAlgorithm for Sha256 {
+    const HASH_ALG: FsVerityHashAlg = FsVerityHashAlg::Sha256;
+}
+
+impl FsVerityAlgorithm for Sha512 {
+    const HASH_ALG: FsVerityHashAlg = FsVerityHashAlg::Sha512;
+}
+
+/// An incremental fs-verity hasher implementing the RustCrypto `digest`
+/// traits (`Update`, `FixedOutputReset`, `OutputSizeUser`, `Reset`) and
+/// `io::Write`, so it composes with generic code written against `Digest`
+/// and can be fed straight from `io::copy`.
+///
+/// Unlike a plain stream hash, fs-verity is a Merkle-tree construction.
+/// Rather than keeping every level-0 digest around for the lifetime of the
+/// hasher (`O(file size)` memory), this keeps one in-progress digest list
+/// per tree level: [`Update::update`] buffers incoming bytes into
+/// `block_size`-sized data blocks, hashes each completed block into the
+/// level-0 list, and whenever a level accumulates enough digests to fill a
+/// parent block, that block is hashed immediately and the result is handed
+/// up to the next level, freeing the level it came from. This bounds
+/// memory use to `O(tree height)` — a handful of blocks — regardless of
+/// file size, while still producing byte-identical digests to folding the
+/// whole tree at once. [`FixedOutputReset::finalize_into_reset`] pads and
+/// collapses whatever partially filled levels remain up to the root and
+/// applies the final descriptor hashing step.
+#[derive(Clone)]
+pub struct FsVerityHasher<D: FsVerityAlgorithm> {
+    block_size: usize,
+    salt: Vec<u8>,
+    block_buf: Vec<u8>,
+    levels: Vec<Vec<GenericArray<u8, D::OutputSize>>>,
+    total_len: u64,
+}
+
+impl<D: FsVerityAlgorithm> FsVerityHasher<D> {
+    /// Creates a new hasher using the block size and salt from `config`.
+    /// `config.hash_alg` is ignored in favor of `D`; callers pick the
+    /// algorithm by choosing `D` (e.g. `FsVerityHasher::<Sha256>::new(..)`).
+    pub fn new(config: &FsVerityConfig) -> Self {
+        FsVerityHasher {
+            block_size: config.block_size,
+            salt: config.salt.clone(),
+            block_buf: Vec::with_capacity(config.block_size),
+            levels: vec![Vec::new()],
+            total_len: 0,
+        }
+    }
+
+    fn hash_salted_block(&self, block: &[u8]) -> GenericArray<u8, D::OutputSize> {
+        let mut hasher = D::new();
+        hasher.update(&self.salt);
+        hasher.update(block);
+        hasher.finalize()
+    }
+
+    fn digests_per_block(&self) -> usize {
+        self.block_size / D::OutputSize::to_usize()
+    }
+
+    fn hash_digests(&self, digests: &[GenericArray<u8, D::OutputSize>]) -> GenericArray<u8, D::OutputSize> {
+        let block = pack_digest_block(self.block_size, digests.iter().map(|d| d.as_slice()));
+        self.hash_salted_block(&block)
+    }
+
+    /// Pushes `digest` onto `level`, cascading completed parent blocks up
+    /// the tree so that no level ever holds more than `digests_per_block`
+    /// entries.
+    fn push_digest(&mut self, mut level: usize, mut digest: GenericArray<u8, D::OutputSize>) {
+        let digests_per_block = self.digests_per_block();
+        loop {
+            if self.levels.len() == level {
+                self.levels.push(Vec::new());
+            }
+            self.levels[level].push(digest);
+
+            if self.levels[level].len() < digests_per_block {
+                break;
+            }
+
+            digest = self.hash_digests(&self.levels[level]);
+            self.levels[level].clear();
+            level += 1;
+        }
+    }
+}
+
+impl<D: FsVerityAlgorithm> Update for FsVerityHasher<D> {
+    fn update(&mut self, data: &[u8]) {
+        self.total_len += data.len() as u64;
+        self.block_buf.extend_from_slice(data);
+
+        while self.block_buf.len() >= self.block_size {
+            let block: Vec<u8> = self.block_buf.drain(..self.block_size).collect();
+            let digest = self.hash_salted_block(&block);
+            self.push_digest(0, digest);
+        }
+    }
+}
+
+impl<D: FsVerityAlgorithm> io::Write for FsVerityHasher<D> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Update::update(self, buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<D: FsVerityAlgorithm> OutputSizeUser for FsVerityHasher<D> {
+    type OutputSize = D::OutputSize;
+}
+
+/// Uses the block size and salt of [`FsVerityConfig::default`].
+impl<D: FsVerityAlgorithm> Default for FsVerityHasher<D> {
+    fn default() -> Self {
+        Self::new(&FsVerityConfig::default())
+    }
+}
+
+impl<D: FsVerityAlgorithm> HashMarker for FsVerityHasher<D> {}
+
+impl<D: FsVerityAlgorithm> FixedOutput for FsVerityHasher<D> {
+    fn finalize_into(mut self, out: &mut GenericArray<u8, Self::OutputSize>) {
+        FixedOutputReset::finalize_into_reset(&mut self, out);
+    }
+}
+
+impl<D: FsVerityAlgorithm> FixedOutputReset for FsVerityHasher<D> {
+    fn finalize_into_reset(&mut self, out: &mut GenericArray<u8, Self::OutputSize>) {
+        if !self.block_buf.is_empty() || self.total_len == 0 {
+            let block = zero_pad(&self.block_buf, self.block_size);
+            let digest = self.hash_salted_block(&block);
+            self.push_digest(0, digest);
+        }
+
+        // Every level holds at most `digests_per_block - 1` digests at
+        // this point (a full level would already have cascaded away in
+        // `push_digest`), so collapsing bottom-up needs only one hash per
+        // non-empty level. The single exception is the topmost level with
+        // data: if it holds exactly one digest, that digest already *is*
+        // the Merkle tree root and must not be hashed again.
+        let mut carry = None;
+        let levels = std::mem::replace(&mut self.levels, vec![Vec::new()]);
+        let top = levels.len() - 1;
+        for (level, mut digests) in levels.into_iter().enumerate() {
+            if let Some(c) = carry.take() {
+                digests.push(c);
+            }
+            if digests.is_empty() {
+                continue;
+            }
+            carry = Some(if level == top && digests.len() == 1 {
+                digests.remove(0)
+            } else {
+                self.hash_digests(&digests)
+            });
+        }
+        let root_hash = carry.expect("at least one block is always hashed above");
+
+        let digest_size = D::OutputSize::to_usize();
+        let mut salt = [0u8; 32];
+        salt[..self.salt.len()].copy_from_slice(&self.salt);
+        let mut root_hash_field = [0u8; 64];
+        root_hash_field[..digest_size].copy_from_slice(&root_hash);
+
+        let descriptor = FsVerityDescriptor {
+            version: 1,
+            hash_algorithm: D::HASH_ALG.id() as u8,
+            log_blocksize: self.block_size.trailing_zeros() as u8,
+            salt_size: self.salt.len() as u8,
+            sig_size: 0,
+            data_size: self.total_len,
+            root_hash: root_hash_field,
+            salt,
+            reserved: [0u8; 144],
+        };
+
+        let mut descriptor_hasher = D::new();
+        descriptor_hasher.update(descriptor.to_bytes());
+        out.copy_from_slice(&descriptor_hasher.finalize());
+
+        Reset::reset(self);
+    }
+}
+
+impl<D: FsVerityAlgorithm> Reset for FsVerityHasher<D> {
+    fn reset(&mut self) {
+        self.block_buf.clear();
+        self.levels = vec![Vec::new()];
+        self.total_len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{FsVerityConfig, FsVerityHashAlg};
+
+    #[test]
+    fn hasher_matches_compute_digest() {
+        let config = FsVerityConfig::default();
+        let data = vec![0xAB; 10_000];
+        let expected = compute_digest(&config, &data);
+
+        let mut hasher = FsVerityHasher::<Sha256>::new(&config);
+        Update::update(&mut hasher, &data);
+        let digest = hasher.finalize_fixed();
+
+        assert_eq!(expected.digest, digest.to_vec());
+    }
+
+    #[test]
+    fn hasher_streamed_byte_by_byte_matches_compute_digest() {
+        // A tiny block size forces many Merkle tree levels even for a
+        // small input, exercising push_digest's multi-level cascade.
+        let config = FsVerityConfig::new(FsVerityHashAlg::Sha256, 64, Vec::new());
+        let data: Vec<u8> = (0u8..=255).cycle().take(640).collect();
+        let expected = compute_digest(&config, &data);
+
+        let mut hasher = FsVerityHasher::<Sha256>::new(&config);
+        for byte in &data {
+            io::Write::write_all(&mut hasher, std::slice::from_ref(byte)).unwrap();
+        }
+        let digest = hasher.finalize_fixed();
+
+        assert_eq!(expected.digest, digest.to_vec());
+    }
+
+    #[test]
+    fn hasher_reset_produces_independent_digest() {
+        let config = FsVerityConfig::default();
+        let mut hasher = FsVerityHasher::<Sha256>::new(&config);
+
+        Update::update(&mut hasher, b"first input");
+        let mut first = GenericArray::default();
+        FixedOutputReset::finalize_into_reset(&mut hasher, &mut first);
+
+        Update::update(&mut hasher, b"second input");
+        let mut second = GenericArray::default();
+        FixedOutputReset::finalize_into_reset(&mut hasher, &mut second);
+
+        assert_ne!(first, second);
+        assert_eq!(first.to_vec(), compute_digest(&config, b"first input").digest);
+        assert_eq!(second.to_vec(), compute_digest(&config, b"second input").digest);
+    }
+}
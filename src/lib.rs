@@ -1,6 +1,3 @@
-#![feature(str_split_once)]
-#![feature(slice_fill)]
-
 mod config;
 
 pub use config::*;
@@ -11,3 +8,7 @@ pub mod linux;
 mod digest;
 
 pub use digest::*;
+
+pub mod signature;
+
+pub mod verify;
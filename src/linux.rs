@@ -0,0 +1,119 @@
+//! Thin wrappers around the Linux `fsverity` ioctls (`FS_IOC_ENABLE_VERITY`,
+//! `FS_IOC_MEASURE_VERITY`), as documented in
+//! `Documentation/filesystems/fsverity.rst`.
+
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+use crate::config::{FsVerityConfig, FsVerityHashAlg};
+use crate::digest::FsVerityDigest;
+
+#[repr(C)]
+struct FsverityEnableArg {
+    version: u32,
+    hash_algorithm: u32,
+    block_size: u32,
+    salt_size: u32,
+    salt_ptr: u64,
+    sig_size: u32,
+    reserved1: u32,
+    sig_ptr: u64,
+    reserved: [u64; 11],
+}
+
+#[repr(C)]
+struct FsverityDigest {
+    digest_algorithm: u16,
+    digest_size: u16,
+    // followed by `digest_size` bytes of digest data, filled in by the
+    // caller-sized buffer this struct is the header of.
+}
+
+// _IOW('f', 133, struct fsverity_enable_arg), computed as
+// (1 << 30) | (size_of::<FsverityEnableArg>() << 16) | ('f' << 8) | 133.
+const FS_IOC_ENABLE_VERITY: u64 = 0x4080_6685;
+// _IOWR('f', 134, struct fsverity_digest), computed as
+// (3 << 30) | (size_of::<FsverityDigest>() << 16) | ('f' << 8) | 134.
+const FS_IOC_MEASURE_VERITY: u64 = 0xc004_6686;
+
+/// Enables fs-verity on `file`, with an optional detached signature to pass
+/// through `fsverity_enable_arg::sig_ptr`/`sig_size`.
+///
+/// The signature, when present, must be a DER-encoded PKCS#7 signature over
+/// the `fsverity_formatted_digest` produced by [`crate::signature::format_digest_for_signing`],
+/// matching what `fsverity sign` / `fsverity enable --signature` produce.
+pub fn enable_verity<F: AsRawFd>(file: &F, config: &FsVerityConfig, signature: Option<&[u8]>) -> io::Result<()> {
+    let (sig_ptr, sig_size) = match signature {
+        Some(sig) => (sig.as_ptr() as u64, sig.len() as u32),
+        None => (0, 0),
+    };
+
+    let arg = FsverityEnableArg {
+        version: 1,
+        hash_algorithm: config.hash_alg.id() as u32,
+        block_size: config.block_size as u32,
+        salt_size: config.salt.len() as u32,
+        salt_ptr: config.salt.as_ptr() as u64,
+        sig_size,
+        reserved1: 0,
+        sig_ptr,
+        reserved: [0; 11],
+    };
+
+    let ret = unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_ENABLE_VERITY, &arg) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Reads back the fs-verity digest the kernel computed for `file`.
+///
+/// `hash_alg` only sizes the input buffer; the kernel treats
+/// `fsverity_digest::digest_size` as a capacity and overwrites both it and
+/// `digest_algorithm` with the file's *actual* on-disk values before
+/// filling in the digest (it errors only if the buffer is too small, never
+/// if the caller guessed the wrong algorithm). So the returned digest is
+/// always tagged with what the kernel reported, and this errors instead of
+/// silently mixing real digest bytes with leftover zero padding if that
+/// doesn't match what the caller asked for.
+pub fn measure_verity<F: AsRawFd>(file: &F, hash_alg: FsVerityHashAlg) -> io::Result<FsVerityDigest> {
+    let digest_size = hash_alg.digest_size();
+    let mut buf = vec![0u8; std::mem::size_of::<FsverityDigest>() + digest_size];
+
+    {
+        let header = buf.as_mut_ptr() as *mut FsverityDigest;
+        unsafe {
+            (*header).digest_algorithm = 0;
+            (*header).digest_size = digest_size as u16;
+        }
+    }
+
+    let ret = unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_MEASURE_VERITY, buf.as_mut_ptr()) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let header_size = std::mem::size_of::<FsverityDigest>();
+    let (actual_algorithm, actual_size) = {
+        let header = buf.as_ptr() as *const FsverityDigest;
+        unsafe { ((*header).digest_algorithm, (*header).digest_size as usize) }
+    };
+
+    let actual_hash_alg = FsVerityHashAlg::from_id(actual_algorithm).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("kernel reported unknown fs-verity hash algorithm id {actual_algorithm}"),
+        )
+    })?;
+    if actual_hash_alg != hash_alg {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("file is verity-protected with {actual_hash_alg}, not the requested {hash_alg}"),
+        ));
+    }
+
+    let digest = buf[header_size..header_size + actual_size].to_vec();
+
+    Ok(FsVerityDigest { hash_alg: actual_hash_alg, digest })
+}
@@ -0,0 +1,130 @@
+//! Pure-Rust, offline fs-verity verification.
+//!
+//! This re-derives a file's fs-verity digest entirely in userspace from a
+//! previously [`exported Merkle tree`](crate::export_merkle_tree) and the
+//! file's data, without going through the Linux `FS_IOC_MEASURE_VERITY`
+//! ioctl. It exists for platforms and filesystems that don't support
+//! fs-verity, e.g. to check artifacts in CI or on non-Linux readers.
+
+use crate::config::FsVerityConfig;
+use crate::digest::{descriptor_bytes, digest_from_descriptor, hash_block, hash_leaves};
+use crate::FsVerityDigest;
+
+/// Reports the first tree block found to disagree with its expected hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyError {
+    /// Offset, in bytes from the start of the file, of the first data
+    /// range whose integrity could not be confirmed.
+    pub failing_offset: u64,
+}
+
+/// Walks `data` and a previously [`exported`](crate::export_merkle_tree)
+/// `tree` bottom-up, re-hashing every data block and every stored tree
+/// block, and compares the result against `expected`.
+///
+/// Returns `Ok(())` if every block matches, or the offset of the first
+/// block (data block, or tree block covering a range of them) whose
+/// content does not match the hash its parent records.
+pub fn verify_offline(config: &FsVerityConfig, data: &[u8], tree: &[u8], expected: &FsVerityDigest) -> Result<(), VerifyError> {
+    let digest_size = config.hash_alg.digest_size();
+    let digests_per_block = config.block_size / digest_size;
+
+    let mut expected_digests = hash_leaves(config, data);
+
+    // Mirrors export_merkle_tree's `while level.len() > 1`: as long as the
+    // current level holds more than one digest, it was written out, and
+    // must be read back and re-verified against the digests the level
+    // below it (data blocks, on the first pass) produced.
+    let mut tree_offset = 0;
+    let mut leaves_per_block = 1u64;
+    while expected_digests.len() > 1 {
+        let level_len = expected_digests.len();
+        let blocks_in_level = level_len.div_ceil(digests_per_block);
+        let level_bytes = blocks_in_level * config.block_size;
+        let level_region = tree
+            .get(tree_offset..tree_offset + level_bytes)
+            .ok_or(VerifyError { failing_offset: 0 })?;
+
+        let mut next_digests = Vec::with_capacity(blocks_in_level);
+        for (block_idx, block) in level_region.chunks(config.block_size).enumerate() {
+            let start = block_idx * digests_per_block;
+            let end = ((block_idx + 1) * digests_per_block).min(level_len);
+            for (i, entry) in (start..end).zip(block.chunks(digest_size)) {
+                if entry != expected_digests[i].as_slice() {
+                    return Err(VerifyError {
+                        failing_offset: i as u64 * leaves_per_block * config.block_size as u64,
+                    });
+                }
+            }
+            next_digests.push(hash_block(config.hash_alg, &config.salt, block));
+        }
+
+        tree_offset += level_bytes;
+        expected_digests = next_digests;
+        leaves_per_block *= digests_per_block as u64;
+    }
+
+    let root_hash = expected_digests.remove(0);
+    let descriptor = tree.get(tree_offset..).ok_or(VerifyError { failing_offset: 0 })?;
+    let expected_descriptor = descriptor_bytes(config, data.len() as u64, &root_hash);
+    if descriptor != expected_descriptor.as_slice() {
+        return Err(VerifyError { failing_offset: 0 });
+    }
+
+    let digest = digest_from_descriptor(config.hash_alg, descriptor);
+    if &digest == expected {
+        Ok(())
+    } else {
+        Err(VerifyError { failing_offset: 0 })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FsVerityHashAlg;
+    use crate::export_merkle_tree;
+
+    // Small block size so the test data spans several Merkle tree levels.
+    fn test_config() -> FsVerityConfig {
+        FsVerityConfig::new(FsVerityHashAlg::Sha256, 64, vec![1, 2, 3])
+    }
+
+    #[test]
+    fn export_then_verify_offline_round_trips() {
+        let config = test_config();
+        let data: Vec<u8> = (0u8..=255).cycle().take(640).collect();
+
+        let mut tree = Vec::new();
+        let digest = export_merkle_tree(&config, &data, &mut tree).unwrap();
+
+        assert!(verify_offline(&config, &data, &tree, &digest).is_ok());
+    }
+
+    #[test]
+    fn verify_offline_detects_corrupted_data() {
+        let config = test_config();
+        let data: Vec<u8> = (0u8..=255).cycle().take(640).collect();
+
+        let mut tree = Vec::new();
+        let digest = export_merkle_tree(&config, &data, &mut tree).unwrap();
+
+        let mut corrupted = data.clone();
+        corrupted[100] ^= 0xff;
+
+        assert!(verify_offline(&config, &corrupted, &tree, &digest).is_err());
+    }
+
+    #[test]
+    fn verify_offline_detects_corrupted_tree() {
+        let config = test_config();
+        let data: Vec<u8> = (0u8..=255).cycle().take(640).collect();
+
+        let mut tree = Vec::new();
+        let digest = export_merkle_tree(&config, &data, &mut tree).unwrap();
+
+        tree[0] ^= 0xff;
+
+        assert!(verify_offline(&config, &data, &tree, &digest).is_err());
+    }
+}